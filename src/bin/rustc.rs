@@ -1,15 +1,19 @@
 use std::env;
 
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher, SipHasher};
 use std::path::Path;
 use std::process::Output;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+
+use rustc_serialize::json::Json;
 
 use cargo::ops;
 use cargo::ops::{ExecEngine, CommandPrototype, CompileOptions};
 use cargo::core::{Package, Source};
 use cargo::sources::PathSource;
 use cargo::util::important_paths::find_root_manifest_for_cwd;
-use cargo::util::{CliResult, CliError, Config};
+use cargo::util::{CliResult, CliError, Config, human};
 use cargo::util::{CargoResult, ProcessError, ProcessBuilder};
 
 #[derive(RustcDecodable, Debug)]
@@ -25,6 +29,15 @@ struct Options {
     flag_verbose: bool,
 
     flag_release: bool,
+    flag_lib: bool,
+    flag_bin: Vec<String>,
+    flag_example: Vec<String>,
+    flag_test: Vec<String>,
+    flag_bench: Vec<String>,
+    flag_all_crates: bool,
+    flag_rustc: Option<String>,
+    flag_rustc_wrapper: bool,
+    flag_print: bool,
 }
 
 pub const USAGE: &'static str = "
@@ -43,14 +56,47 @@ Options:
     --manifest-path PATH    Path to the manifest to fetch depednencies for
     -v, --verbose           Use verbose output
     --release               Build artifacts in release mode, with optimizations
+    --lib                   Build only this package's library
+    --bin NAME              Build only the specified binary
+    --example NAME          Build only the specified example
+    --test NAME             Build only the specified test
+    --bench NAME            Build only the specified benchmark
+    --all-crates            Pass the trailing arguments to every rustc
+                            invocation, including dependencies
+    --rustc PATH            Invoke PATH instead of rustc to compile the
+                            selected target (every crate with --all-crates)
+    --rustc-wrapper         Fall back to $RUSTC_WRAPPER for --rustc if it is
+                            not given explicitly (off by default)
+    --print                 Print the rustc invocation(s) for the selected
+                            target as JSON instead of running them
+                            (not compatible with --all-crates)
 
 The <pkgid> specified (defaults to the current package) will have all of its
 dependencies compiled, and then the package itself will be compiled. This
 command requires that a lockfile is available and dependencies have been
 fetched.
 
-All of the trailing arguments are passed through to the *final* rustc
-invocation, not any of the dependencies.
+All of the trailing arguments are passed through to the rustc invocation for
+the selected target (by default the package's binaries, or its library if it
+has no binaries; otherwise whichever target was chosen with
+--lib/--bin/--example/--test/--bench), not any of the
+dependencies. Pass --all-crates to instead have every rustc invocation in the
+build, dependencies included, receive the trailing arguments.
+
+--rustc substitutes a different compiler driver for the selected target (for
+every crate in the build when --all-crates is also passed), prepending it to the rustc invocation Cargo assembled so it still sees all of
+the --extern, -L, and --crate-name arguments Cargo generated. $RUSTC_WRAPPER
+is only consulted when --rustc-wrapper is also passed, since an environment
+that happens to set it (e.g. for sccache) should not silently change what a
+bare `cargo rustc` does or what --print reports.
+
+--print skips running the rustc invocation(s) for the selected target and
+instead writes a JSON array to stdout, one entry per invocation, with the
+program, args and env that Cargo would have executed. This is meant for
+external tools (IDEs, lint drivers) that need the exact command line Cargo
+would have used. Since --all-crates matches every crate in the graph, it
+cannot be combined with --print: every dependency would be skipped rather
+than compiled, leaving the build incomplete.
 
 Dependencies will not be recompiled if they do not need to be, but the package
 specified will always be compiled. The compiler will receive a number of
@@ -58,25 +104,46 @@ arguments unconditionally such as --extern, -L, etc. Note that dependencies are
 recompiled when the flags they're compiled with change, so it is not allowed to
 manually compile a package's dependencies and then compile the package against
 the artifacts just generated.
+
+The compiled target's output filename is suffixed with a hash of the trailing
+arguments, so switching between different sets of trailing arguments (or back
+to a plain `cargo build`) produces distinct artifacts for the target rather
+than silently overwriting a build made with different flags.
 ";
 
 struct RustcEngine {
     args: Option<Vec<String>>,
     targets: Vec<String>,
+    all_crates: bool,
+    rustc: Option<String>,
+    print: bool,
+    printed: Arc<Mutex<Vec<Json>>>,
 }
 
 impl ExecEngine for RustcEngine {
     fn exec(&self, command: CommandPrototype) -> Result<(), ProcessError> {
-        append_rustc_opts(command, &self.args, &self.targets).exec()
+        let (process, name_matches) = append_rustc_opts(command, &self.args, &self.targets,
+                                                         self.all_crates, &self.rustc);
+        if self.print && name_matches {
+            self.printed.lock().unwrap().push(process_to_json(&process));
+            return Ok(());
+        }
+        process.exec()
     }
 
     fn exec_with_output(&self, command: CommandPrototype) -> Result<Output, ProcessError> {
-        append_rustc_opts(command, &self.args, &self.targets).exec_with_output()
+        // --print only short-circuits `exec`, since this path is used to capture a
+        // real process's stdout/stderr (e.g. querying the compiler) and there is no
+        // way to fabricate a faithful `Output` without actually running something.
+        let (process, _) = append_rustc_opts(command, &self.args, &self.targets,
+                                              self.all_crates, &self.rustc);
+        process.exec_with_output()
     }
 }
 
-fn append_rustc_opts(mut command: CommandPrototype, args: &Option<Vec<String>>, targets: &Vec<String>) -> ProcessBuilder {
-    let name_matches = command.get_args().windows(2).find(|&args| {
+fn append_rustc_opts(mut command: CommandPrototype, args: &Option<Vec<String>>, targets: &Vec<String>,
+                      all_crates: bool, rustc: &Option<String>) -> (ProcessBuilder, bool) {
+    let name_matches = all_crates || command.get_args().windows(2).find(|&args| {
         args[0].to_str() == Some("--crate-name") &&
         targets.iter().find(|&target| Some(target.as_ref()) == args[1].to_str()).is_some()
     }).is_some();
@@ -85,9 +152,72 @@ fn append_rustc_opts(mut command: CommandPrototype, args: &Option<Vec<String>>,
         if let &Some(ref args) = args {
             debug!("appending args to cmd; cmd={}; args={:?}", command, args);
             command.args(&args);
+            command.arg(&extra_filename_flag(&args));
+        }
+    }
+
+    let process = command.into_process_builder();
+    let process = if name_matches {
+        match *rustc {
+            Some(ref rustc) => wrap_with_rustc(process, rustc),
+            None => process,
+        }
+    } else {
+        process
+    };
+    (process, name_matches)
+}
+
+// `CompileOptions` has no hook for arbitrary extra rustc args, so Cargo's own
+// fingerprint/freshness tracking never learns about them. The best we can do
+// from here, scoped to just this invocation, is give rustc itself a distinct
+// `-C extra-filename` per distinct set of trailing args: builds with
+// different flags land in different output files instead of clobbering each
+// other, and a plain `cargo build` (no trailing args, no suffix) keeps
+// producing the filename it always has.
+fn extra_filename_flag(args: &[String]) -> String {
+    let mut hasher = SipHasher::new();
+    for arg in args {
+        arg.hash(&mut hasher);
+    }
+    format!("-Cextra-filename=-{:016x}", hasher.finish())
+}
+
+fn wrap_with_rustc(process: ProcessBuilder, rustc: &str) -> ProcessBuilder {
+    debug!("invoking alternate rustc; process={}; rustc={}", process, rustc);
+    let mut wrapped = ProcessBuilder::new(rustc);
+    wrapped.arg(process.get_program());
+    wrapped.args(process.get_args());
+    if let Some(cwd) = process.get_cwd() {
+        wrapped.cwd(cwd);
+    }
+    for (k, v) in process.get_envs().iter() {
+        match *v {
+            Some(ref v) => { wrapped.env(k, v); }
+            None => { wrapped.env_remove(k); }
         }
     }
-    command.into_process_builder()
+    wrapped
+}
+
+fn process_to_json(process: &ProcessBuilder) -> Json {
+    let mut entry = BTreeMap::new();
+    entry.insert("program".to_string(),
+                 Json::String(process.get_program().to_string_lossy().into_owned()));
+    entry.insert("args".to_string(), Json::Array(
+        process.get_args().iter()
+            .map(|a| Json::String(a.to_string_lossy().into_owned()))
+            .collect()
+    ));
+    entry.insert("env".to_string(), Json::Object(
+        process.get_envs().iter()
+            .map(|(k, v)| (k.clone(), match *v {
+                Some(ref v) => Json::String(v.to_string_lossy().into_owned()),
+                None => Json::Null,
+            }))
+            .collect()
+    ));
+    Json::Object(entry)
 }
 
 fn get_package(root: &Path, config: &Config) -> CargoResult<Package> {
@@ -101,6 +231,14 @@ pub fn execute(options: Options, config: &Config) -> CliResult<Option<()>> {
            env::args().collect::<Vec<_>>());
     config.shell().set_verbose(options.flag_verbose);
 
+    if options.flag_print && options.flag_all_crates {
+        return Err(CliError::from_boxed(
+            human("--print cannot be combined with --all-crates: every \
+                   invocation would be skipped instead of compiled, so \
+                   dependencies would never be built"),
+            101));
+    }
+
     let root = try!(find_root_manifest_for_cwd(options.flag_manifest_path));
 
     let package = try!(get_package(&root, &config));
@@ -113,14 +251,65 @@ pub fn execute(options: Options, config: &Config) -> CliResult<Option<()>> {
             Some(&s[..])
         }).unwrap();
 
-    let bins: Vec<String> = package.targets().iter()
-        .filter(|t| t.is_bin())
-        .map(|t| t.name().to_string())
-        .collect();
+    let no_target_selected = !options.flag_lib &&
+        options.flag_bin.is_empty() &&
+        options.flag_example.is_empty() &&
+        options.flag_test.is_empty() &&
+        options.flag_bench.is_empty();
+
+    let lib = options.flag_lib;
+    let bins = if no_target_selected {
+        package.targets().iter()
+            .filter(|t| t.is_bin())
+            .map(|t| t.name().to_string())
+            .collect()
+    } else {
+        options.flag_bin
+    };
+    let examples = options.flag_example;
+    let tests = options.flag_test;
+    let benches = options.flag_bench;
+
+    // rustc normalizes hyphens to underscores in --crate-name, so any
+    // hyphenated target name has to be normalized the same way or
+    // `append_rustc_opts` will never match that target's invocation.
+    let mut targets: Vec<String> = Vec::new();
+    if lib || (no_target_selected && bins.is_empty()) {
+        targets.push(package.name().replace('-', "_"));
+    }
+    targets.extend(bins.iter().map(|t| t.replace('-', "_")));
+    targets.extend(examples.iter().map(|t| t.replace('-', "_")));
+    targets.extend(tests.iter().map(|t| t.replace('-', "_")));
+    targets.extend(benches.iter().map(|t| t.replace('-', "_")));
+
+    let rustc = options.flag_rustc.or_else(|| {
+        if options.flag_rustc_wrapper {
+            env::var("RUSTC_WRAPPER").ok()
+        } else {
+            None
+        }
+    });
+    let arg_opts = options.arg_opts;
+
+    let printed = Arc::new(Mutex::new(Vec::new()));
 
     let engine = RustcEngine {
-        args: options.arg_opts,
-        targets: bins.clone()
+        args: arg_opts.clone(),
+        targets: targets,
+        all_crates: options.flag_all_crates,
+        rustc: rustc,
+        print: options.flag_print,
+        printed: printed.clone(),
+    };
+
+    let filter = if lib || !bins.is_empty() || !examples.is_empty() ||
+        !tests.is_empty() || !benches.is_empty() {
+        ops::CompileFilter::Only {
+            lib: lib, bins: &bins, examples: &examples,
+            benches: &benches, tests: &tests,
+        }
+    } else {
+        ops::CompileFilter::Everything
     };
 
     let opts = CompileOptions {
@@ -133,16 +322,20 @@ pub fn execute(options: Options, config: &Config) -> CliResult<Option<()>> {
         exec_engine: Some(Arc::new(Box::new(engine))),
         mode: ops::CompileMode::Build,
         release: options.flag_release,
-        filter: if bins.is_empty() {
-            ops::CompileFilter::Everything
-        } else {
-            ops::CompileFilter::Only {
-                lib: false, bins: &bins, examples: &[], benches: &[], tests: &[]
-            }
-        },
+        filter: filter,
     };
 
-    ops::compile(&root, &opts).map(|_| None).map_err(|err| {
+    let result = ops::compile(&root, &opts).map(|_| None).map_err(|err| {
         CliError::from_boxed(err, 101)
-    })
+    });
+
+    if options.flag_print {
+        // Jobs run in parallel, so entries can land in `printed` in whatever order
+        // the scheduler happened to finish them; sort for a deterministic listing.
+        let mut entries = printed.lock().unwrap().clone();
+        entries.sort_by(|a, b| a.to_string().cmp(&b.to_string()));
+        println!("{}", Json::Array(entries));
+    }
+
+    result
 }